@@ -0,0 +1,230 @@
+use crate::pe::PEId;
+use crate::pe::PE;
+use crate::scheduler::Scheduler;
+use crossbeam::deque::{Injector, Steal};
+use lockfree::map::Map;
+use snafu::ResultExt;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("No worker pool registered for PE Type {}.", id))]
+    NoSuchPE { id: PEId },
+
+    #[snafu(display("Worker disconnected before the job completed."))]
+    Disconnected {},
+
+    #[snafu(display("Scheduler Error: {}", source))]
+    SchedulerError { source: crate::scheduler::Error },
+
+    #[snafu(display("PE Error: {}", source))]
+    PEError { source: crate::pe::Error },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A unit of work transferred into a worker. The boxed closure owns its
+/// argument buffers and the completion sender. It is handed either the acquired
+/// PE or the scheduler error that prevented acquisition, so every job resolves
+/// its handle exactly once regardless of how it completes.
+type Job = Box<dyn FnOnce(std::result::Result<&mut PE, crate::scheduler::Error>) + Send>;
+
+/// Handle to an in-flight job. Resolves with the result produced by the worker
+/// once the PE has completed, mirroring a `oneshot` future.
+#[derive(Debug)]
+pub struct JobHandle<T> {
+    rx: Receiver<Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    /// Block until the worker has finished the job and return its result.
+    pub fn wait(self) -> Result<T> {
+        match self.rx.recv() {
+            Ok(r) => r,
+            Err(_) => Err(Error::Disconnected {}),
+        }
+    }
+}
+
+/// A per-`PEId` job queue with the `(generation, Condvar)` pair idle workers
+/// park on and a shutdown flag set when the `JobQueue` is dropped.
+#[derive(Debug)]
+struct Channel {
+    injector: Injector<Job>,
+    parking: (Mutex<usize>, Condvar),
+    shutdown: Mutex<bool>,
+}
+
+impl Channel {
+    fn new() -> Channel {
+        Channel {
+            injector: Injector::new(),
+            parking: (Mutex::new(0), Condvar::new()),
+            shutdown: Mutex::new(false),
+        }
+    }
+
+    fn wake_one(&self) {
+        let (lock, cvar) = &self.parking;
+        let mut generation = lock.lock().unwrap();
+        *generation = generation.wrapping_add(1);
+        cvar.notify_one();
+    }
+
+    fn wake_all(&self) {
+        let (lock, cvar) = &self.parking;
+        let mut generation = lock.lock().unwrap();
+        *generation = generation.wrapping_add(1);
+        cvar.notify_all();
+    }
+}
+
+/// Higher-level dispatch layer on top of [`Scheduler`]. Owns a pool of worker
+/// threads per registered `PEId`; each worker acquires a PE, hands it to the
+/// job closure (which programs, launches and awaits the accelerator) and
+/// releases it afterwards, freeing callers from the manual
+/// acquire/launch/release dance.
+#[derive(Debug)]
+pub struct JobQueue {
+    scheduler: Arc<Scheduler>,
+    channels: Map<PEId, Arc<Channel>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl JobQueue {
+    /// Create a job queue backed by `scheduler`, spawning `workers_per_id`
+    /// worker threads for each of the given PE types.
+    pub fn new(scheduler: Arc<Scheduler>, ids: &[PEId], workers_per_id: usize) -> JobQueue {
+        let channels: Map<PEId, Arc<Channel>> = Map::new();
+        let mut workers = Vec::new();
+
+        for id in ids.iter().copied() {
+            let channel = Arc::new(Channel::new());
+            channels.insert(id, channel.clone());
+
+            for _ in 0..workers_per_id {
+                let channel = channel.clone();
+                let scheduler = scheduler.clone();
+                workers.push(thread::spawn(move || {
+                    worker_loop(id, &scheduler, &channel);
+                }));
+            }
+        }
+
+        JobQueue {
+            scheduler,
+            channels,
+            workers: Mutex::new(workers),
+        }
+    }
+
+    /// Submit a job targeting `id`. The closure receives exclusive access to an
+    /// acquired PE and returns the value to resolve the handle with. Any
+    /// [`crate::pe::Error`] raised inside the closure is surfaced through the
+    /// returned [`JobHandle`].
+    pub fn submit<T, F>(&self, id: PEId, job: F) -> Result<JobHandle<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut PE) -> std::result::Result<T, crate::pe::Error> + Send + 'static,
+    {
+        let channel = match self.channels.get(&id) {
+            Some(c) => c.val().clone(),
+            None => return Err(Error::NoSuchPE { id }),
+        };
+
+        let (tx, rx) = sync_channel(1);
+        let boxed: Job = Box::new(move |pe| {
+            let result = match pe {
+                Ok(pe) => job(pe).context(PEError),
+                Err(e) => Err(e).context(SchedulerError),
+            };
+            // The receiver may have been dropped; ignore in that case.
+            let _ = tx.send(result);
+        });
+
+        channel.injector.push(boxed);
+        channel.wake_one();
+
+        Ok(JobHandle { rx })
+    }
+}
+
+impl Drop for JobQueue {
+    /// Signal shutdown and join all workers. Workers poll the shutdown flag
+    /// while acquiring a PE (see [`ACQUIRE_POLL`]), so a worker blocked on a
+    /// saturated PE type is guaranteed to observe the flag and exit within one
+    /// poll interval rather than deadlocking the join. Any job still queued or
+    /// held by an exiting worker is dropped, resolving its handle with
+    /// [`Error::Disconnected`].
+    fn drop(&mut self) {
+        for c in self.channels.iter() {
+            *c.val().shutdown.lock().unwrap() = true;
+            c.val().wake_all();
+        }
+        for worker in self.workers.lock().unwrap().drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// How often a worker re-checks the shutdown flag while waiting for a PE, so a
+/// saturated PE type cannot keep a worker blocked past a `JobQueue` drop.
+const ACQUIRE_POLL: Duration = Duration::from_millis(100);
+
+fn worker_loop(id: PEId, scheduler: &Scheduler, channel: &Channel) {
+    'worker: loop {
+        let job = match channel.injector.steal() {
+            Steal::Success(job) => Some(job),
+            Steal::Empty | Steal::Retry => None,
+        };
+
+        let job = match job {
+            Some(job) => job,
+            None => {
+                let (lock, cvar) = &channel.parking;
+                let mut generation = lock.lock().unwrap();
+                if *channel.shutdown.lock().unwrap() {
+                    return;
+                }
+                // Re-check after locking to avoid missing a concurrent submit.
+                match channel.injector.steal() {
+                    Steal::Success(job) => job,
+                    Steal::Empty | Steal::Retry => {
+                        let observed = *generation;
+                        while *generation == observed {
+                            generation = cvar.wait(generation).unwrap();
+                        }
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // Acquire a PE, polling so a concurrent shutdown is observed even when
+        // the PE type stays saturated. Dropping `job` on shutdown resolves its
+        // handle with `Disconnected`.
+        let mut pe = loop {
+            if *channel.shutdown.lock().unwrap() {
+                return;
+            }
+            match scheduler.acquire_pe_timeout(id, ACQUIRE_POLL) {
+                Ok(Some(pe)) => break pe,
+                Ok(None) => continue,
+                // Acquisition failed outright (e.g. unknown PE type): surface
+                // the error through the handle and take the next job.
+                Err(e) => {
+                    job(Err(e));
+                    continue 'worker;
+                }
+            }
+        };
+
+        job(Ok(&mut pe));
+        if let Err(e) = scheduler.release_pe(pe) {
+            error!("Failed to release PE after job: {}", e);
+        }
+    }
+}
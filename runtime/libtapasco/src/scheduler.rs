@@ -1,13 +1,17 @@
 use crate::pe::PEId;
 use crate::pe::PE;
 use crossbeam::deque::{Injector, Steal};
+use crossbeam::utils::Backoff;
 use lockfree::map::Map;
 use lockfree::set::Set;
 use memmap::MmapMut;
 use snafu::ResultExt;
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fs::File;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -17,6 +21,9 @@ pub enum Error {
     #[snafu(display("PE Type {} is unknown.", id))]
     NoSuchPE { id: PEId },
 
+    #[snafu(display("No PE type named {} is known.", name))]
+    NoSuchPEName { name: String },
+
     #[snafu(display("PE {} is still active. Can't release it.", pe.id()))]
     PEStillActive { pe: PE },
 
@@ -26,9 +33,59 @@ pub enum Error {
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Free PEs of one type plus a `(generation, Condvar)` pair parked acquirers
+/// wait on until a PE is returned.
+#[derive(Debug)]
+struct PeQueue {
+    injector: Injector<PE>,
+    parking: (Mutex<usize>, Condvar),
+}
+
+impl PeQueue {
+    fn new() -> PeQueue {
+        PeQueue {
+            injector: Injector::new(),
+            parking: (Mutex::new(0), Condvar::new()),
+        }
+    }
+
+    /// Return a PE to a live queue, bumping the generation and waking one
+    /// parked acquirer. Used wherever a PE re-enters a queue that acquirers may
+    /// already be parked on.
+    fn release(&self, pe: PE) {
+        self.injector.push(pe);
+        let (lock, cvar) = &self.parking;
+        let mut generation = lock.lock().unwrap();
+        *generation = generation.wrapping_add(1);
+        cvar.notify_one();
+    }
+}
+
 #[derive(Debug)]
 pub struct Scheduler {
-    pes: Map<PEId, Injector<PE>>,
+    pes: Map<PEId, PeQueue>,
+    names: HashMap<String, PEId>,
+}
+
+/// Symbolic reference to a PE type, parsed from a string that is either a
+/// decimal `PEId` (e.g. `"42"`) or a VLNV/name (e.g. `"arrayinit"`). Resolve it
+/// against a concrete [`Scheduler`] with [`Scheduler::resolve`] to obtain the
+/// numeric `PEId`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeSelector {
+    Id(PEId),
+    Name(String),
+}
+
+impl FromStr for PeSelector {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<PeSelector, Infallible> {
+        Ok(match s.parse::<PEId>() {
+            Ok(id) => PeSelector::Id(id),
+            Err(_) => PeSelector::Name(s.to_string()),
+        })
+    }
 }
 
 impl Scheduler {
@@ -39,9 +96,12 @@ impl Scheduler {
     ) -> Result<Scheduler> {
         let active_pes = Arc::new((Mutex::new(completion), Set::new()));
 
-        let pe_hashed: Map<PEId, Injector<PE>> = Map::new();
+        let pe_hashed: Map<PEId, PeQueue> = Map::new();
+        let mut names: HashMap<String, PEId> = HashMap::new();
 
         for (i, pe) in pes.iter().enumerate() {
+            names.insert(pe.name.to_string(), pe.id as PEId);
+
             let the_pe = PE::new(
                 i,
                 pe.id as PEId,
@@ -53,30 +113,156 @@ impl Scheduler {
             );
 
             match pe_hashed.get(&(pe.id as PEId)) {
-                Some(l) => l.val().push(the_pe),
+                Some(l) => l.val().injector.push(the_pe),
                 None => {
                     trace!("New PE type found: {}.", pe.id);
-                    let v = Injector::new();
-                    v.push(the_pe);
+                    let v = PeQueue::new();
+                    v.injector.push(the_pe);
                     pe_hashed.insert(pe.id as PEId, v);
                 }
             }
         }
 
-        Ok(Scheduler { pes: pe_hashed })
+        Ok(Scheduler {
+            pes: pe_hashed,
+            names,
+        })
+    }
+
+    /// Resolve a symbolic [`PeSelector`] to a concrete `PEId` known to this
+    /// scheduler, returning an error if the id or name is unknown.
+    pub fn resolve(&self, selector: &PeSelector) -> Result<PEId> {
+        match selector {
+            PeSelector::Id(id) => match self.pes.get(id) {
+                Some(_) => Ok(*id),
+                None => Err(Error::NoSuchPE { id: *id }),
+            },
+            PeSelector::Name(name) => self.pe_id_by_name(name),
+        }
+    }
+
+    fn pe_id_by_name(&self, name: &str) -> Result<PEId> {
+        match self.names.get(name) {
+            Some(id) => Ok(*id),
+            None => Err(Error::NoSuchPEName {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Acquire a free PE selected by its human-readable `name` rather than its
+    /// numeric id.
+    pub fn acquire_pe_by_name(&self, name: &str) -> Result<PE> {
+        let id = self.pe_id_by_name(name)?;
+        self.acquire_pe(id)
+    }
+
+    /// Non-blocking acquire: performs a single steal and returns `Ok(None)` if
+    /// no PE of the requested type is currently free.
+    pub fn try_acquire_pe(&self, id: PEId) -> Result<Option<PE>> {
+        match self.pes.get(&id) {
+            Some(l) => match l.val().injector.steal() {
+                Steal::Success(pe) => Ok(Some(pe)),
+                Steal::Empty | Steal::Retry => Ok(None),
+            },
+            None => Err(Error::NoSuchPE { id }),
+        }
     }
 
+    /// Acquire a free PE of the given type, blocking until one becomes
+    /// available.
+    ///
+    /// The uncontended case stays on the lock-free `steal()` fast path. Only
+    /// when no PE is free does the thread park on the type's `Condvar`; it is
+    /// woken by `release_pe` returning a matching PE. The generation counter
+    /// guarded by the mutex, combined with a re-check of the injector under the
+    /// lock, closes the lost-wakeup window between the failed steal and the
+    /// `wait`.
     pub fn acquire_pe(&self, id: PEId) -> Result<PE> {
         match self.pes.get(&id) {
-            Some(l) => loop {
-                match l.val().steal() {
-                    Steal::Success(pe) => return Ok(pe),
-                    Steal::Empty => (),
-                    Steal::Retry => (),
+            Some(l) => {
+                let q = l.val();
+                let backoff = Backoff::new();
+                loop {
+                    match q.injector.steal() {
+                        Steal::Success(pe) => return Ok(pe),
+                        Steal::Empty | Steal::Retry => (),
+                    }
+
+                    // Adaptively spin first: most PEs are freed within a few
+                    // microseconds, so a short snooze is far cheaper than a
+                    // full park/unpark round-trip. Only park once the backoff
+                    // has escalated as far as it usefully can.
+                    if !backoff.is_completed() {
+                        backoff.snooze();
+                        continue;
+                    }
+
+                    let (lock, cvar) = &q.parking;
+                    let mut generation = lock.lock().unwrap();
+                    // Re-check under the lock so a release that happens between
+                    // the steal above and the wait below is not missed.
+                    match q.injector.steal() {
+                        Steal::Success(pe) => return Ok(pe),
+                        Steal::Empty | Steal::Retry => (),
+                    }
+                    let observed = *generation;
+                    while *generation == observed {
+                        generation = cvar.wait(generation).unwrap();
+                    }
+                    backoff.reset();
                 }
-                thread::yield_now();
-            },
-            None => return Err(Error::NoSuchPE { id }),
+            }
+            None => Err(Error::NoSuchPE { id }),
+        }
+    }
+
+    /// Like [`acquire_pe`](Scheduler::acquire_pe), but gives up after
+    /// `timeout` and returns `Ok(None)` if no PE of the requested type became
+    /// free in time.
+    ///
+    /// Spurious wakeups and wakeups for a generation bump that another
+    /// acquirer won the race for are handled by recomputing the remaining
+    /// duration against the original deadline, so the total wait never exceeds
+    /// `timeout`. Callers dispatching to a contended PE type can use this to
+    /// implement backpressure or fall back to a different accelerator instead
+    /// of blocking forever.
+    pub fn acquire_pe_timeout(&self, id: PEId, timeout: Duration) -> Result<Option<PE>> {
+        match self.pes.get(&id) {
+            Some(l) => {
+                let q = l.val();
+                let deadline = Instant::now() + timeout;
+                loop {
+                    match q.injector.steal() {
+                        Steal::Success(pe) => return Ok(Some(pe)),
+                        Steal::Empty | Steal::Retry => (),
+                    }
+
+                    if deadline.checked_duration_since(Instant::now()).is_none() {
+                        return Ok(None);
+                    }
+
+                    let (lock, cvar) = &q.parking;
+                    let mut generation = lock.lock().unwrap();
+                    match q.injector.steal() {
+                        Steal::Success(pe) => return Ok(Some(pe)),
+                        Steal::Empty | Steal::Retry => (),
+                    }
+                    let observed = *generation;
+                    while *generation == observed {
+                        let remaining = match deadline.checked_duration_since(Instant::now()) {
+                            Some(r) => r,
+                            None => return Ok(None),
+                        };
+                        let (g, res) = cvar.wait_timeout(generation, remaining).unwrap();
+                        generation = g;
+                        if res.timed_out() {
+                            return Ok(None);
+                        }
+                    }
+                }
+            }
+            None => Err(Error::NoSuchPE { id }),
         }
     }
 
@@ -84,16 +270,49 @@ impl Scheduler {
         ensure!(!pe.active(), PEStillActive { pe: pe });
 
         match self.pes.get(&pe.type_id()) {
-            Some(l) => l.val().push(pe),
+            Some(l) => l.val().release(pe),
             None => return Err(Error::NoSuchPE { id: *pe.type_id() }),
         }
         Ok(())
     }
 
+    /// Run `f` inside a borrowed scope that can launch jobs across many PE
+    /// instances in parallel and joins them before returning.
+    ///
+    /// The handle passed to `f` exposes [`PeScope::spawn`], which acquires a PE
+    /// of the requested type, runs the closure with `&mut PE` and releases the
+    /// PE again. `scope` blocks until every spawned job has finished and
+    /// returns the first error observed (from acquiring, the job closure, or
+    /// releasing). Because the work is joined before `scope` returns, the
+    /// spawned closures only need to outlive the `scope` call rather than being
+    /// `'static`, so they may borrow from the caller's stack.
+    pub fn scope<'env, F>(&'env self, f: F) -> Result<()>
+    where
+        F: FnOnce(&PeScope<'_, 'env>),
+    {
+        let error: Arc<Mutex<Option<Error>>> = Arc::new(Mutex::new(None));
+        crossbeam::thread::scope(|s| {
+            let pe_scope = PeScope {
+                scheduler: self,
+                inner: s,
+                error: error.clone(),
+            };
+            f(&pe_scope);
+        })
+        .expect("a job spawned inside Scheduler::scope panicked");
+
+        // All spawned jobs have been joined; take the first recorded error out
+        // of the shared slot directly rather than relying on the refcount.
+        match error.lock().unwrap().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
     pub fn reset_interrupts(&self) -> Result<()> {
         for v in self.pes.iter() {
             let mut remove_pes = Vec::new();
-            let mut maybe_pe = v.val().steal();
+            let mut maybe_pe = v.val().injector.steal();
 
             while let Steal::Success(pe) = maybe_pe {
                 pe.enable_interrupt().context(PEError)?;
@@ -101,14 +320,58 @@ impl Scheduler {
                     pe.reset_interrupt(true).context(PEError)?;
                 }
                 remove_pes.push(pe);
-                maybe_pe = v.val().steal();
+                maybe_pe = v.val().injector.steal();
             }
 
             for pe in remove_pes.into_iter() {
-                v.val().push(pe);
+                v.val().release(pe);
             }
         }
 
         Ok(())
     }
 }
+
+/// Handle handed to the closure passed to [`Scheduler::scope`]. Runs each
+/// spawned job on its own scoped thread; all of them are joined when the scope
+/// ends.
+pub struct PeScope<'scope, 'env: 'scope> {
+    scheduler: &'env Scheduler,
+    inner: &'scope crossbeam::thread::Scope<'env>,
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+impl<'scope, 'env: 'scope> PeScope<'scope, 'env> {
+    /// Acquire a PE of type `id`, run `f` against it and release it again. The
+    /// job runs concurrently with other spawned jobs; the first error produced
+    /// by any job is returned from [`Scheduler::scope`].
+    pub fn spawn<F, T>(&self, id: PEId, f: F)
+    where
+        F: FnOnce(&mut PE) -> std::result::Result<T, crate::pe::Error> + Send + 'env,
+        T: Send + 'env,
+    {
+        let scheduler = self.scheduler;
+        let error = self.error.clone();
+        self.inner.spawn(move |_| {
+            match scheduler.acquire_pe(id) {
+                Ok(mut pe) => {
+                    let job_result = f(&mut pe).context(PEError);
+                    let release_result = scheduler.release_pe(pe);
+                    record_first(&error, job_result.map(|_| ()));
+                    record_first(&error, release_result);
+                }
+                Err(e) => record_first(&error, Err(e)),
+            }
+        });
+    }
+}
+
+/// Store `result`'s error in `slot` unless an earlier error is already present.
+fn record_first(slot: &Arc<Mutex<Option<Error>>>, result: Result<()>) {
+    if let Err(e) = result {
+        let mut guard = slot.lock().unwrap();
+        if guard.is_none() {
+            *guard = Some(e);
+        }
+    }
+}